@@ -0,0 +1,210 @@
+use crate::Result;
+use image::imageops::{overlay, FilterType};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+
+/// A single border measurement, expressed either as an exact pixel count or
+/// as a percentage of the card's shorter edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    /// An exact width, in pixels.
+    Absolute(u32),
+    /// A percentage (0-100) of the card's shorter edge.
+    Percent(f64),
+}
+
+impl Side {
+    fn resolve(self, reference_edge: u32) -> u32 {
+        match self {
+            Side::Absolute(px) => px,
+            Side::Percent(pct) => ((pct / 100.0) * reference_edge as f64).round() as u32,
+        }
+    }
+}
+
+/// The border width on each of the four sides of a card.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sides {
+    pub top: Side,
+    pub right: Side,
+    pub bottom: Side,
+    pub left: Side,
+}
+
+impl Sides {
+    /// The same `side` on all four edges.
+    pub fn uniform(side: Side) -> Self {
+        Sides {
+            top: side,
+            right: side,
+            bottom: side,
+            left: side,
+        }
+    }
+
+    /// Resolves each side to pixels, using `width`/`height` to determine the
+    /// card's shorter edge for `Side::Percent` measurements.
+    fn resolve(self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let reference_edge = width.min(height);
+        (
+            self.top.resolve(reference_edge),
+            self.right.resolve(reference_edge),
+            self.bottom.resolve(reference_edge),
+            self.left.resolve(reference_edge),
+        )
+    }
+}
+
+/// How the border region surrounding a card's content gets filled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FillMode {
+    /// A flat background color.
+    Solid(Rgba<u16>),
+    /// The card content itself, stretched to bleed under the frame.
+    Stretch,
+}
+
+/// A configurable frame around a card, reused across all cells in a grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Border {
+    pub sides: Sides,
+    pub fill: FillMode,
+    pub resample: FilterType,
+    pub corner_radius_px: u32,
+}
+
+impl Border {
+    /// Composes `card` onto a canvas sized `content + borders`, fills the
+    /// added border region per `self.fill`, then rounds the canvas corners.
+    pub fn apply(&self, card: &DynamicImage) -> Result<DynamicImage> {
+        let (card_width, card_height) = card.dimensions();
+        let (top, right, bottom, left) = self.sides.resolve(card_width, card_height);
+        let canvas_width = card_width + left + right;
+        let canvas_height = card_height + top + bottom;
+
+        let mut canvas = match &self.fill {
+            FillMode::Solid(color) => ImageBuffer::from_pixel(canvas_width, canvas_height, *color),
+            FillMode::Stretch => card
+                .resize_exact(canvas_width, canvas_height, self.resample)
+                .to_rgba16(),
+        };
+
+        overlay(&mut canvas, &card.to_rgba16(), left as i64, top as i64);
+        let radius_px = self
+            .corner_radius_px
+            .min(canvas_width.min(canvas_height) / 2);
+        round_corners(&mut canvas, radius_px);
+
+        Ok(DynamicImage::ImageRgba16(canvas))
+    }
+}
+
+fn round_corners(img: &mut ImageBuffer<Rgba<u16>, Vec<u16>>, radius_px: u32) {
+    let (width, height) = img.dimensions();
+
+    let top_left_center = (radius_px, radius_px);
+    round_a_corner(
+        img,
+        0..top_left_center.0,
+        0..top_left_center.1,
+        radius_px,
+        top_left_center,
+    );
+
+    let top_right_center = (width - radius_px, radius_px);
+    round_a_corner(
+        img,
+        top_right_center.0..width,
+        0..top_right_center.1,
+        radius_px,
+        top_right_center,
+    );
+
+    let bottom_left_center = (radius_px, height - radius_px);
+    round_a_corner(
+        img,
+        0..bottom_left_center.0,
+        bottom_left_center.1..height,
+        radius_px,
+        bottom_left_center,
+    );
+
+    let bottom_right_center = (width - radius_px, height - radius_px);
+    round_a_corner(
+        img,
+        bottom_right_center.0..width,
+        bottom_right_center.1..height,
+        radius_px,
+        bottom_right_center,
+    );
+}
+
+fn round_a_corner(
+    img: &mut ImageBuffer<Rgba<u16>, Vec<u16>>,
+    x_range: std::ops::Range<u32>,
+    y_range: std::ops::Range<u32>,
+    radius_px: u32,
+    center_point: (u32, u32),
+) {
+    let squared = radius_px * radius_px;
+    for y in y_range {
+        let y_offset = y as i32 - center_point.1 as i32;
+        for x in x_range.clone() {
+            let x_offset = x as i32 - center_point.0 as i32;
+            if (x_offset * x_offset + y_offset * y_offset) as u32 > squared {
+                img.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{round_corners, Border, FillMode, Side, Sides};
+    use image::imageops::FilterType;
+    use image::{DynamicImage, ImageBuffer, Rgba};
+
+    #[test]
+    fn side_absolute_resolves_to_itself() {
+        assert_eq!(Side::Absolute(12).resolve(300), 12);
+    }
+
+    #[test]
+    fn side_percent_resolves_against_reference_edge() {
+        assert_eq!(Side::Percent(10.0).resolve(300), 30);
+    }
+
+    #[test]
+    fn sides_resolve_uses_the_shorter_edge_as_reference() {
+        let sides = Sides::uniform(Side::Percent(10.0));
+        assert_eq!(sides.resolve(300, 200), (20, 20, 20, 20));
+    }
+
+    #[test]
+    fn round_corners_clears_outside_the_radius() {
+        let mut img: ImageBuffer<Rgba<u16>, Vec<u16>> =
+            ImageBuffer::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+        round_corners(&mut img, 5);
+
+        // The far corner pixel is well outside the radius and gets cleared...
+        assert_eq!(*img.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+        // ...but the center of the image is untouched.
+        assert_eq!(*img.get_pixel(10, 10), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn apply_clamps_a_corner_radius_larger_than_the_canvas() {
+        let card =
+            DynamicImage::ImageRgba16(ImageBuffer::from_pixel(10, 10, Rgba([255, 255, 255, 255])));
+        let border = Border {
+            sides: Sides::uniform(Side::Absolute(2)),
+            fill: FillMode::Solid(Rgba([0, 0, 0, 0])),
+            resample: FilterType::Triangle,
+            corner_radius_px: 500,
+        };
+
+        // A 14x14 canvas with a 500px radius would underflow `width -
+        // radius_px` unless `apply` clamps it first; this just needs to not
+        // panic.
+        border.apply(&card).unwrap();
+    }
+}
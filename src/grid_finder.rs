@@ -1,7 +1,6 @@
-use crate::transparent_pixel;
-use crate::{FpcError, Result};
+use crate::{is_background, Background, FpcError, Result};
 use image::math::Rect;
-use image::DynamicImage;
+use image::{DynamicImage, ImageBuffer, Rgba};
 use itertools::Itertools;
 use std::ops::Range;
 
@@ -10,6 +9,263 @@ struct GridFinder {}
 
 impl GridFinder {}
 
+/// A point in continuous image-space coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// The coefficients of a 3x3 projective transform.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Homography {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+    f: f64,
+    g: f64,
+    h: f64,
+}
+
+fn solve_2x2(a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) -> Option<(f64, f64)> {
+    let det = a * d - b * c;
+    if det == 0.0 {
+        return None;
+    }
+    let rx = (e * d - b * f) / det;
+    let ry = (a * f - e * c) / det;
+    Some((rx, ry))
+}
+
+fn line_intersect(p0: Point, p1: Point, q0: Point, q1: Point) -> Option<Point> {
+    let (t, _s) = solve_2x2(
+        p1.x - p0.x,
+        -(q1.x - q0.x),
+        p1.y - p0.y,
+        -(q1.y - q0.y),
+        q0.x - p0.x,
+        q0.y - p0.y,
+    )?;
+    Some(Point {
+        x: p0.x + t * (p1.x - p0.x),
+        y: p0.y + t * (p1.y - p0.y),
+    })
+}
+
+fn is_convex_quad(tl: Point, tr: Point, br: Point, bl: Point) -> bool {
+    let Some(crossing) = line_intersect(tl, br, tr, bl) else {
+        return false;
+    };
+
+    let on_segment = |a: Point, b: Point, p: Point| {
+        let t = if (b.x - a.x).abs() > (b.y - a.y).abs() {
+            (p.x - a.x) / (b.x - a.x)
+        } else {
+            (p.y - a.y) / (b.y - a.y)
+        };
+        (0.0..=1.0).contains(&t)
+    };
+
+    on_segment(tl, br, crossing) && on_segment(tr, bl, crossing)
+}
+
+/// Computes the forward homography mapping the unit square onto `tl,tr,br,bl`.
+pub(crate) fn square_to_quad(tl: Point, tr: Point, br: Point, bl: Point) -> Homography {
+    let dx1 = tr.x - br.x;
+    let dx2 = bl.x - br.x;
+    let dx3 = tl.x - tr.x + br.x - bl.x;
+    let dy1 = tr.y - br.y;
+    let dy2 = bl.y - br.y;
+    let dy3 = tl.y - tr.y + br.y - bl.y;
+
+    let (g, h) = if dx3 == 0.0 && dy3 == 0.0 {
+        (0.0, 0.0)
+    } else {
+        solve_2x2(dx1, dx2, dy1, dy2, dx3, dy3).unwrap_or((0.0, 0.0))
+    };
+
+    Homography {
+        a: tr.x - tl.x + g * tr.x,
+        b: bl.x - tl.x + h * bl.x,
+        c: tl.x,
+        d: tr.y - tl.y + g * tr.y,
+        e: bl.y - tl.y + h * bl.y,
+        f: tl.y,
+        g,
+        h,
+    }
+}
+
+pub(crate) fn apply_homography(homography: &Homography, u: f64, v: f64) -> (f64, f64) {
+    let Homography {
+        a,
+        b,
+        c,
+        d,
+        e,
+        f,
+        g,
+        h,
+    } = *homography;
+    let w = g * u + h * v + 1.0;
+    ((a * u + b * v + c) / w, (d * u + e * v + f) / w)
+}
+
+pub(crate) fn sample_bilinear(img: &ImageBuffer<Rgba<u16>, Vec<u16>>, x: f64, y: f64) -> Rgba<u16> {
+    let max_x = img.width() as f64 - 1.0;
+    let max_y = img.height() as f64 - 1.0;
+    let x = x.clamp(0.0, max_x.max(0.0));
+    let y = y.clamp(0.0, max_y.max(0.0));
+
+    let x0 = x.floor() as u32;
+    let y0 = y.floor() as u32;
+    let x1 = (x0 + 1).min(img.width() - 1);
+    let y1 = (y0 + 1).min(img.height() - 1);
+    let tx = x - x0 as f64;
+    let ty = y - y0 as f64;
+
+    let p00 = img.get_pixel(x0, y0).0;
+    let p10 = img.get_pixel(x1, y0).0;
+    let p01 = img.get_pixel(x0, y1).0;
+    let p11 = img.get_pixel(x1, y1).0;
+
+    let mut out = [0u16; 4];
+    for i in 0..4 {
+        let top = p00[i] as f64 * (1.0 - tx) + p10[i] as f64 * tx;
+        let bottom = p01[i] as f64 * (1.0 - tx) + p11[i] as f64 * tx;
+        out[i] = (top * (1.0 - ty) + bottom * ty).round() as u16;
+    }
+    Rgba(out)
+}
+
+fn find_extreme_point(
+    img: &DynamicImage,
+    x_range: Range<u32>,
+    y_range: Range<u32>,
+    corner: (u32, u32),
+    background: Background,
+    tolerance: u8,
+) -> Option<Point> {
+    let mut best: Option<(u32, Point)> = None;
+    for y in y_range {
+        for x in x_range.clone() {
+            if is_background(img, x, y, background, tolerance) {
+                continue;
+            }
+            let dist = x.abs_diff(corner.0) + y.abs_diff(corner.1);
+            if best.map_or(true, |(best_dist, _)| dist < best_dist) {
+                best = Some((
+                    dist,
+                    Point {
+                        x: x as f64,
+                        y: y as f64,
+                    },
+                ));
+            }
+        }
+    }
+    best.map(|(_, point)| point)
+}
+
+fn find_quad_corners(
+    img: &DynamicImage,
+    bounds: Rect,
+    background: Background,
+    tolerance: u8,
+) -> Result<(Point, Point, Point, Point)> {
+    let mid_x = bounds.x + bounds.width / 2;
+    let mid_y = bounds.y + bounds.height / 2;
+    let right = bounds.x + bounds.width;
+    let bottom = bounds.y + bounds.height;
+
+    let tl = find_extreme_point(
+        img,
+        bounds.x..mid_x,
+        bounds.y..mid_y,
+        (bounds.x, bounds.y),
+        background,
+        tolerance,
+    )
+    .ok_or(FpcError::CornerNotFound("top-left"))?;
+    let tr = find_extreme_point(
+        img,
+        mid_x..right,
+        bounds.y..mid_y,
+        (right, bounds.y),
+        background,
+        tolerance,
+    )
+    .ok_or(FpcError::CornerNotFound("top-right"))?;
+    let br = find_extreme_point(
+        img,
+        mid_x..right,
+        mid_y..bottom,
+        (right, bottom),
+        background,
+        tolerance,
+    )
+    .ok_or(FpcError::CornerNotFound("bottom-right"))?;
+    let bl = find_extreme_point(
+        img,
+        bounds.x..mid_x,
+        mid_y..bottom,
+        (bounds.x, bottom),
+        background,
+        tolerance,
+    )
+    .ok_or(FpcError::CornerNotFound("bottom-left"))?;
+
+    Ok((tl, tr, br, bl))
+}
+
+// True if `tl,tr,br,bl` already sit on the corners of `bounds` (within
+// `epsilon` pixels), i.e. warping would just add resampling blur for nothing.
+fn is_axis_aligned(tl: Point, tr: Point, br: Point, bl: Point, bounds: Rect, epsilon: f64) -> bool {
+    let right = (bounds.x + bounds.width) as f64;
+    let bottom = (bounds.y + bounds.height) as f64;
+    let (left, top) = (bounds.x as f64, bounds.y as f64);
+
+    let close = |p: Point, x: f64, y: f64| (p.x - x).abs() <= epsilon && (p.y - y).abs() <= epsilon;
+
+    close(tl, left, top)
+        && close(tr, right, top)
+        && close(br, right, bottom)
+        && close(bl, left, bottom)
+}
+
+/// Perspective-corrects a rotated or keystoned card sheet by warping the
+/// bordered region's four outer corners onto an axis-aligned rectangle.
+/// Returns `img` unchanged if the corners aren't a convex quad or are already
+/// axis-aligned, rather than re-rendering through a needless resample.
+pub fn deskew(img: &DynamicImage, background: Background, tolerance: u8) -> Result<DynamicImage> {
+    let bounds = find_bordered_bounds(img, background, tolerance)?;
+    let (tl, tr, br, bl) = find_quad_corners(img, bounds, background, tolerance)?;
+
+    if !is_convex_quad(tl, tr, br, bl) {
+        return Ok(img.clone());
+    }
+
+    let epsilon = 2.0_f64.max(0.005 * bounds.width.min(bounds.height) as f64);
+    if is_axis_aligned(tl, tr, br, bl, bounds, epsilon) {
+        return Ok(img.clone());
+    }
+
+    let homography = square_to_quad(tl, tr, br, bl);
+    let source = img.to_rgba16();
+    let mut rectified = ImageBuffer::new(bounds.width, bounds.height);
+    for y in 0..bounds.height {
+        let v = (y as f64 + 0.5) / bounds.height as f64;
+        for x in 0..bounds.width {
+            let u = (x as f64 + 0.5) / bounds.width as f64;
+            let (sx, sy) = apply_homography(&homography, u, v);
+            rectified.put_pixel(x, y, sample_bilinear(&source, sx, sy));
+        }
+    }
+    Ok(DynamicImage::ImageRgba16(rectified))
+}
+
 fn find_pixels<'a>(
     range: impl Iterator<Item = u32> + 'a,
     mut predicate: impl FnMut(u32) -> bool + 'a,
@@ -38,10 +294,17 @@ fn group_sequences(iter: impl Iterator<Item = u32>) -> Vec<Range<u32>> {
     })
 }
 
-pub fn find_grid_cells(img: &DynamicImage) -> Result<Vec<Rect>> {
-    let bordered_bounds = find_bordered_bounds(img)?;
+pub fn find_grid_cells(
+    img: &DynamicImage,
+    background: Background,
+    tolerance: u8,
+) -> Result<Vec<Rect>> {
+    let deskewed = deskew(img, background, tolerance)?;
+    let img = &deskewed;
+
+    let bordered_bounds = find_bordered_bounds(img, background, tolerance)?;
     let (left_thickness, top_thickness, right_thickness, bottom_thickness) =
-        find_border_widths(img, bordered_bounds)?;
+        find_border_widths(img, bordered_bounds, background, tolerance)?;
     let inside_border_bounds = Rect {
         x: left_thickness,
         y: top_thickness,
@@ -50,11 +313,11 @@ pub fn find_grid_cells(img: &DynamicImage) -> Result<Vec<Rect>> {
     };
     let vert_grid_line_ranges = find_grid_line_ranges(
         bordered_bounds.x..bordered_bounds.x + bordered_bounds.width,
-        move |xx| transparent_pixel(img, xx, inside_border_bounds.y),
+        move |xx| is_background(img, xx, inside_border_bounds.y, background, tolerance),
     );
     let horiz_grid_line_ranges = find_grid_line_ranges(
         bordered_bounds.y..bordered_bounds.y + bordered_bounds.height,
-        move |yy| transparent_pixel(img, inside_border_bounds.x, yy),
+        move |yy| is_background(img, inside_border_bounds.x, yy, background, tolerance),
     );
     let mut rects: Vec<Rect> = vec![];
     for (top, bottom) in horiz_grid_line_ranges.iter().tuple_windows() {
@@ -80,25 +343,25 @@ fn find_grid_line_ranges<'a>(
     group_sequences(pixels_iter)
 }
 
-fn find_bordered_bounds(img: &DynamicImage) -> Result<Rect> {
+fn find_bordered_bounds(img: &DynamicImage, background: Background, tolerance: u8) -> Result<Rect> {
     // From the center of each edge, search inward until we find the first non-blank pixel.
     // We assume that this is the start of the border around the entire image grid.
 
     let center_x = img.width() / 2;
     let center_y = img.height() / 2;
     let left = (0..img.width())
-        .find(|xx| !transparent_pixel(img, *xx, center_y))
+        .find(|xx| !is_background(img, *xx, center_y, background, tolerance))
         .ok_or(FpcError::MissingBorder("top"))?;
     let right = (0..img.width())
         .rev()
-        .find(|xx| !transparent_pixel(img, *xx, center_y))
+        .find(|xx| !is_background(img, *xx, center_y, background, tolerance))
         .ok_or(FpcError::MissingBorder("right"))?;
     let top = (0..img.height())
-        .find(|yy| !transparent_pixel(img, center_x, *yy))
+        .find(|yy| !is_background(img, center_x, *yy, background, tolerance))
         .ok_or(FpcError::MissingBorder("top"))?;
     let bottom = (0..img.height())
         .rev()
-        .find(|yy| !transparent_pixel(img, center_x, *yy))
+        .find(|yy| !is_background(img, center_x, *yy, background, tolerance))
         .ok_or(FpcError::MissingBorder("bottom"))?;
     Ok(Rect {
         x: left,
@@ -115,11 +378,17 @@ fn scan_range(
     range.find(predicate).ok_or(FpcError::BlankNotFound)
 }
 
-fn find_border_widths(img: &DynamicImage, bounds: Rect) -> Result<(u32, u32, u32, u32)> {
+fn find_border_widths(
+    img: &DynamicImage,
+    bounds: Rect,
+    background: Background,
+    tolerance: u8,
+) -> Result<(u32, u32, u32, u32)> {
     let (x, y, w, h) = (bounds.x, bounds.y, bounds.width, bounds.height);
     let center_x = w / 2;
     let center_y = h / 2;
-    let is_blank = transparent_pixel;
+    let is_blank =
+        |img: &DynamicImage, px: u32, py: u32| is_background(img, px, py, background, tolerance);
 
     let top_thickness = scan_range(y..y + h, |yy| is_blank(img, center_x, *yy))?;
     let bottom_thickness = h - scan_range((y..y + h).rev(), |yy| is_blank(img, center_x, *yy))? - 1;
@@ -136,14 +405,19 @@ fn find_border_widths(img: &DynamicImage, bounds: Rect) -> Result<(u32, u32, u32
 
 #[cfg(test)]
 mod test {
-    use crate::grid_finder::{find_border_widths, find_grid_cells};
+    use crate::grid_finder::{
+        find_border_widths, find_bordered_bounds, find_grid_cells, is_axis_aligned, is_convex_quad,
+        line_intersect, square_to_quad, Point,
+    };
+    use crate::Background;
     use image::math::Rect;
     use image::open;
 
     #[test]
     fn test_border_width() {
         let img = open("test_inputs/3x5 grid.png").expect("Failed to open image");
-        let foo = find_border_widths(&img).unwrap();
+        let bounds = find_bordered_bounds(&img, Background::Transparent, 0).unwrap();
+        let foo = find_border_widths(&img, bounds, Background::Transparent, 0).unwrap();
 
         assert_eq!(foo, (0, 0, 0, 0));
     }
@@ -151,8 +425,92 @@ mod test {
     #[test]
     fn test_grid() {
         let img = open("test_inputs/3x5 grid.png").expect("Failed to open image");
-        let foo = find_grid_cells(&img).unwrap();
+        let foo = find_grid_cells(&img, Background::Transparent, 0).unwrap();
 
         assert_eq!(Vec::<Rect>::default(), foo);
     }
+
+    #[test]
+    fn line_intersect_crosses_at_center() {
+        let got = line_intersect(
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 10.0, y: 10.0 },
+            Point { x: 0.0, y: 10.0 },
+            Point { x: 10.0, y: 0.0 },
+        )
+        .unwrap();
+        assert_eq!(got, Point { x: 5.0, y: 5.0 });
+    }
+
+    #[test]
+    fn line_intersect_parallel_is_none() {
+        let got = line_intersect(
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 10.0, y: 0.0 },
+            Point { x: 0.0, y: 1.0 },
+            Point { x: 10.0, y: 1.0 },
+        );
+        assert_eq!(got, None);
+    }
+
+    #[test]
+    fn convex_quad_diagonals_cross() {
+        let tl = Point { x: 0.0, y: 0.0 };
+        let tr = Point { x: 10.0, y: 0.0 };
+        let br = Point { x: 10.0, y: 10.0 };
+        let bl = Point { x: 0.0, y: 10.0 };
+        assert!(is_convex_quad(tl, tr, br, bl));
+    }
+
+    #[test]
+    fn bowtie_quad_is_not_convex() {
+        // Swapping br and bl makes the "quad" self-intersect.
+        let tl = Point { x: 0.0, y: 0.0 };
+        let tr = Point { x: 10.0, y: 0.0 };
+        let br = Point { x: 0.0, y: 10.0 };
+        let bl = Point { x: 10.0, y: 10.0 };
+        assert!(!is_convex_quad(tl, tr, br, bl));
+    }
+
+    #[test]
+    fn axis_aligned_quad_matching_bounds_is_aligned() {
+        let bounds = Rect {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 200,
+        };
+        let tl = Point { x: 0.0, y: 0.0 };
+        let tr = Point { x: 100.0, y: 0.0 };
+        let br = Point { x: 100.0, y: 200.0 };
+        let bl = Point { x: 0.0, y: 200.0 };
+        assert!(is_axis_aligned(tl, tr, br, bl, bounds, 2.0));
+    }
+
+    #[test]
+    fn keystoned_quad_is_not_axis_aligned() {
+        let bounds = Rect {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 200,
+        };
+        let tl = Point { x: 0.0, y: 0.0 };
+        let tr = Point { x: 90.0, y: 10.0 };
+        let br = Point { x: 100.0, y: 200.0 };
+        let bl = Point { x: 0.0, y: 200.0 };
+        assert!(!is_axis_aligned(tl, tr, br, bl, bounds, 2.0));
+    }
+
+    #[test]
+    fn square_to_quad_is_identity_for_unit_square() {
+        let tl = Point { x: 0.0, y: 0.0 };
+        let tr = Point { x: 1.0, y: 0.0 };
+        let br = Point { x: 1.0, y: 1.0 };
+        let bl = Point { x: 0.0, y: 1.0 };
+        let homography = square_to_quad(tl, tr, br, bl);
+
+        let got = super::apply_homography(&homography, 0.25, 0.75);
+        assert_eq!(got, (0.25, 0.75));
+    }
 }
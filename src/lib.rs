@@ -1,11 +1,15 @@
+mod border;
 mod grid_finder;
+mod qr;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+pub use crate::border::{Border, FillMode, Side, Sides};
 use crate::grid_finder::find_grid_cells;
-use image::imageops::{overlay, FilterType};
+use image::imageops::FilterType;
 use image::math::Rect;
-use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageError, Pixel, Rgba};
+use image::{DynamicImage, GenericImage, GenericImageView, ImageError, Pixel, Rgba};
 use std::ffi::OsStr;
-use std::ops::Range;
 use std::path::Path;
 use thiserror::Error;
 
@@ -17,19 +21,79 @@ pub enum FpcError {
     #[error("a blank row was not found")]
     BlankNotFound,
 
+    #[error("could not locate the {0} corner of the card sheet")]
+    CornerNotFound(&'static str),
+
+    #[error("the detected sheet corners do not form a convex quadrilateral")]
+    NotConvex,
+
     #[error("an underlying image error")]
     ImageError(#[from] ImageError),
 
+    #[error("an error writing PNG data")]
+    PngEncodingError(#[from] png::EncodingError),
+
+    #[error("an IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("found a QR code's finder patterns, but decoding its payload isn't implemented yet")]
+    QrDecodingUnsupported,
+
     #[error("An unknown and hopefully unused error.")]
     Unknown,
 }
 
 pub type Result<T> = std::result::Result<T, FpcError>;
 
+/// What counts as "blank" space around and between cards when scanning for
+/// the grid. Most exported sheets have a genuinely transparent backdrop, but
+/// scanned or photographed sheets are usually opaque, so the blank space has
+/// to be matched against a reference color instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    /// Fully transparent (alpha == 0) pixels.
+    Transparent,
+    /// A solid backdrop color, matched within a per-channel tolerance.
+    Color(Rgba<u16>),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Transparent
+    }
+}
+
+/// Returns `true` if the pixel in `img` at (`x`,`y`) counts as part of
+/// `background`. For `Background::Color`, a pixel matches when every RGB
+/// channel is within `tolerance` of the reference color; alpha is ignored,
+/// since opaque scans have no meaningful alpha channel.
+pub fn is_background(
+    img: &DynamicImage,
+    x: u32,
+    y: u32,
+    background: Background,
+    tolerance: u8,
+) -> bool {
+    match background {
+        Background::Transparent => transparent_pixel(img, x, y),
+        Background::Color(reference) => {
+            let pixel = img.get_pixel(x, y).0;
+            let reference = reference.0;
+            (0..3).all(|channel| {
+                let actual = pixel[channel] as i32;
+                // `Rgba<u16>` colors carry 16-bit channels; scale back down
+                // to the 8-bit space `get_pixel` samples in.
+                let expected = (reference[channel] / 257) as i32;
+                (actual - expected).unsigned_abs() <= tolerance as u32
+            })
+        }
+    }
+}
+
 // extract_images_from_image_grid
 // - takes aspect ratio (w/h)
 // - takes padding (horiz, vert)
-// - background color (CSS color)
+// - border (sides + fill + corner radius)
 // - output directory (default './')
 // - output file stem (default 'image'
 //
@@ -41,19 +105,24 @@ pub type Result<T> = std::result::Result<T, FpcError>;
 //    c. expand to desired AR
 //    d. ensure fits in grid box
 //    e. create output image
-//         i. if background color, fill image with rounded corners
-//        ii. copy from original image into output image
+//         i. compose onto a canvas sized content + border, filled per border.fill
+//        ii. round the canvas corners per border.corner_radius_px
 //       iii. write to output file
 
 pub fn extract_images_from_image_grid(
     img: &DynamicImage,
     aspect_ratio: f64,
     max_width: u32,
-    background_color: Rgba<u16>,
+    border: &Border,
+    background: Background,
+    tolerance: u8,
+    output_dpi: u32,
+    resample: FilterType,
+    read_card_ids: bool,
     output_directory: impl AsRef<OsStr>,
     output_file_stem: impl AsRef<OsStr>,
 ) -> Result<()> {
-    let cells = find_grid_cells(img)?;
+    let cells = find_grid_cells(img, background, tolerance)?;
     output_debug_image(img, &cells)?;
 
     for (i, rect) in cells.iter().enumerate() {
@@ -63,92 +132,106 @@ pub fn extract_images_from_image_grid(
         let path = Path::new(output_directory.as_ref())
             .join(filename)
             .with_extension("png");
-        // Rgba([65535u16, 65535, 65535, 65535]
-        let new_image = make_sub_image(img, rect, background_color)?;
-        let new_image_bounds = new_image.bounds();
-        let scaled_image = scale_to_constraints(
-            &DynamicImage::ImageRgba16(new_image),
-            new_image_bounds,
+
+        if read_card_ids {
+            let cropped = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
+            match qr::locate_card_id(&cropped) {
+                Ok(Some(card_id)) => std::fs::write(path.with_extension("txt"), card_id)?,
+                Ok(None) => {}
+                Err(FpcError::QrDecodingUnsupported) => {
+                    println!(
+                        "cell {}: found a QR code, but decoding it isn't implemented yet; skipping",
+                        i
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let rescaled_image = render_cell(
+            img,
+            rect,
             aspect_ratio,
             max_width,
+            border,
+            output_dpi,
+            resample,
         )?;
 
-        let corner_radius = ((1.0 / 8.0) * 300.0) as u32; // 1/8in with 300dpi
-        let rounded_image = round_the_corners(&scaled_image, corner_radius)?;
-        let rescaled_image = rescale_to_72dpi_1in(aspect_ratio, rounded_image);
-
-        rescaled_image.save(path)?;
+        save_png_with_dpi(&rescaled_image, &path, output_dpi)?;
     }
 
     Ok(())
 }
 
-fn rescale_to_72dpi_1in(aspect_ratio: f64, rounded_image: DynamicImage) -> DynamicImage {
-    let new_width = (1.5 * 72.0) as u32;
+/// Renders a single grid cell into a finished, framed, dpi-rescaled card
+/// image: crop `rect` out of `img`, fit it to `aspect_ratio`/`max_width`,
+/// apply `border`, then rescale to `output_dpi`. Shared by the native save
+/// loop above and the in-memory WASM binding, neither of which needs to
+/// touch disk to get here.
+fn render_cell(
+    img: &DynamicImage,
+    rect: &Rect,
+    aspect_ratio: f64,
+    max_width: u32,
+    border: &Border,
+    output_dpi: u32,
+    resample: FilterType,
+) -> Result<DynamicImage> {
+    let cropped = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
+    let cropped_bounds = cropped.bounds();
+    let scaled_image = scale_to_constraints(&cropped, cropped_bounds, aspect_ratio, max_width)?;
+
+    let framed_image = border.apply(&scaled_image)?;
+    Ok(rescale_to_dpi_1in(
+        aspect_ratio,
+        output_dpi,
+        resample,
+        framed_image,
+    ))
+}
+
+fn rescale_to_dpi_1in(
+    aspect_ratio: f64,
+    dpi: u32,
+    resample: FilterType,
+    rounded_image: DynamicImage,
+) -> DynamicImage {
+    let new_width = (1.5 * dpi as f64) as u32;
     let rescaled_image = rounded_image.resize(
         new_width,
         (new_width as f64 * aspect_ratio) as u32,
-        FilterType::Triangle,
+        resample,
     );
     rescaled_image
 }
 
-fn round_the_corners(img: &DynamicImage, corner_radius_px: u32) -> Result<DynamicImage> {
-    let mut rounded_image = img.clone();
-    let (x, y, width, height) = rounded_image.bounds();
-
-    let top_left_center = (x + corner_radius_px, y + corner_radius_px);
-    round_a_corner(
-        &mut rounded_image,
-        x..top_left_center.0,
-        y..top_left_center.1,
-        corner_radius_px,
-        top_left_center,
-    );
-    let top_right_center = (x + width - corner_radius_px, y + corner_radius_px);
-    round_a_corner(
-        &mut rounded_image,
-        top_right_center.0..x + width,
-        y..top_right_center.1,
-        corner_radius_px,
-        top_right_center,
-    );
-    let bottom_left_center = (x + corner_radius_px, y + height - corner_radius_px);
-    round_a_corner(
-        &mut rounded_image,
-        x..bottom_left_center.0,
-        bottom_left_center.1..y + height,
-        corner_radius_px,
-        bottom_left_center,
-    );
-    let bottom_right_center = (x + width - corner_radius_px, y + height - corner_radius_px);
-    round_a_corner(
-        &mut rounded_image,
-        bottom_right_center.0..x + width,
-        bottom_right_center.1..y + height,
-        corner_radius_px,
-        bottom_right_center,
-    );
-    Ok(rounded_image)
+/// Saves `img` as a PNG at `path`, embedding a `pHYs` chunk so that print and
+/// layout tools treat the image as `dpi`-resolution rather than assuming 96dpi.
+fn save_png_with_dpi(img: &DynamicImage, path: &Path, dpi: u32) -> Result<()> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+
+    let file = std::fs::File::create(path)?;
+    let writer = std::io::BufWriter::new(file);
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let pixels_per_meter = dpi_to_pixels_per_meter(dpi);
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: png::Unit::Meter,
+    }));
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgba.into_raw())?;
+    Ok(())
 }
 
-fn round_a_corner(
-    img: &mut DynamicImage,
-    x_range: Range<u32>,
-    y_range: Range<u32>,
-    corner_radius_px: u32,
-    center_point: (u32, u32),
-) {
-    let squared = corner_radius_px * corner_radius_px;
-    for y in y_range {
-        let y_offset = y as i32 - center_point.1 as i32;
-        for x in x_range.clone() {
-            let x_offset = x as i32 - center_point.0 as i32;
-            if (x_offset * x_offset + y_offset * y_offset) as u32 > squared {
-                img.put_pixel(x, y, Rgba([0, 0, 0, 0]));
-            }
-        }
-    }
+fn dpi_to_pixels_per_meter(dpi: u32) -> u32 {
+    (dpi as f64 / 0.0254).round() as u32
 }
 
 fn scale_to_constraints(
@@ -182,18 +265,6 @@ fn scale_to_constraints(
     Ok(final_image)
 }
 
-fn make_sub_image(
-    img: &DynamicImage,
-    rect: &Rect,
-    background_color: Rgba<u16>,
-) -> Result<ImageBuffer<Rgba<u16>, Vec<u16>>> {
-    // TODO: add rounded corners.
-    let mut new_image = ImageBuffer::from_pixel(rect.width, rect.height, background_color);
-    let sub_image = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
-    overlay(&mut new_image, &sub_image.to_rgba16(), 0, 0);
-    Ok(new_image)
-}
-
 fn output_debug_image(img: &DynamicImage, cells: &Vec<Rect>) -> Result<()> {
     let mut img_copy = img.clone();
     for rect in cells {
@@ -239,16 +310,20 @@ fn draw_line(
     range.for_each(|i| set_pixel(img, i, at));
 }
 
-pub fn find_bounding_boxes(img: DynamicImage) -> Result<Vec<Rect>> {
+pub fn find_bounding_boxes(
+    img: DynamicImage,
+    background: Background,
+    tolerance: u8,
+) -> Result<Vec<Rect>> {
     let bounds = img.bounds();
     println!("bounds: {:?}", bounds);
     let center = ((bounds.0 + bounds.2) / 2, (bounds.1 + bounds.3) / 2);
     println!("center: {:?}", center);
-    let left_edge = scan_horiz(&img, center, -1)?;
-    let right_edge = scan_horiz(&img, center, 1)?;
-    let top_edge = scan_vert(&img, center, -1)?;
+    let left_edge = scan_horiz(&img, center, -1, background, tolerance)?;
+    let right_edge = scan_horiz(&img, center, 1, background, tolerance)?;
+    let top_edge = scan_vert(&img, center, -1, background, tolerance)?;
     println!("top edge: {}", top_edge);
-    let bottom_edge = scan_vert(&img, center, 1)?;
+    let bottom_edge = scan_vert(&img, center, 1, background, tolerance)?;
     println!("bottom edge: {}", bottom_edge);
 
     Ok(vec![Rect {
@@ -259,15 +334,16 @@ pub fn find_bounding_boxes(img: DynamicImage) -> Result<Vec<Rect>> {
     }])
 }
 
-fn scan_horiz(img: &DynamicImage, center: (u32, u32), delta: i32) -> Result<u32> {
+fn scan_horiz(
+    img: &DynamicImage,
+    center: (u32, u32),
+    delta: i32,
+    background: Background,
+    tolerance: u8,
+) -> Result<u32> {
     let mut edge = center.0 as i32;
     while edge >= 0 && (edge as u32) < img.width() {
-        if (0..img.height()).all(|y| {
-            let pixel = img.get_pixel(edge as u32, y);
-
-            // channel 3 is the alpha channel
-            pixel.channels()[3] == 0
-        }) {
+        if (0..img.height()).all(|y| is_background(img, edge as u32, y, background, tolerance)) {
             println!("Found horiz: {}", edge);
             return Ok(edge as u32);
         }
@@ -276,15 +352,16 @@ fn scan_horiz(img: &DynamicImage, center: (u32, u32), delta: i32) -> Result<u32>
     Err(FpcError::Unknown)
 }
 
-fn scan_vert(img: &DynamicImage, center: (u32, u32), delta: i32) -> Result<u32> {
+fn scan_vert(
+    img: &DynamicImage,
+    center: (u32, u32),
+    delta: i32,
+    background: Background,
+    tolerance: u8,
+) -> Result<u32> {
     let mut edge = center.1 as i32;
     while edge >= 0 && (edge as u32) < img.height() {
-        if (0..img.width()).all(|x| {
-            let pixel = img.get_pixel(x, edge as u32);
-
-            // channel 3 is the alpha channel
-            pixel.channels()[3] == 0
-        }) {
+        if (0..img.width()).all(|x| is_background(img, x, edge as u32, background, tolerance)) {
             println!("Found vert: {}", edge);
             return Ok(edge as u32);
         }
@@ -295,14 +372,38 @@ fn scan_vert(img: &DynamicImage, center: (u32, u32), delta: i32) -> Result<u32>
 
 #[cfg(test)]
 mod test {
-    use crate::find_bounding_boxes;
+    use crate::{dpi_to_pixels_per_meter, find_bounding_boxes, is_background, Background};
     use image::math::Rect;
-    use image::open;
+    use image::{open, DynamicImage, ImageBuffer, Rgba};
+
+    #[test]
+    fn dpi_converts_to_pixels_per_meter() {
+        assert_eq!(dpi_to_pixels_per_meter(300), 11811);
+        assert_eq!(dpi_to_pixels_per_meter(72), 2835);
+    }
+
+    #[test]
+    fn color_background_matches_within_tolerance() {
+        let white =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([255, 255, 255, 255])));
+        let background = Background::Color(Rgba([255 * 257, 255 * 257, 255 * 257, 255 * 257]));
+
+        assert!(is_background(&white, 0, 0, background, 0));
+    }
+
+    #[test]
+    fn color_background_rejects_outside_tolerance() {
+        let gray = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([200; 4])));
+        let background = Background::Color(Rgba([255 * 257, 255 * 257, 255 * 257, 255 * 257]));
+
+        assert!(!is_background(&gray, 0, 0, background, 10));
+    }
 
     #[test]
     fn circle_test() {
         let img = open("test_inputs/circle.png").expect("Failed to open image");
-        let boxes = find_bounding_boxes(img).expect("Failed to get bounding box");
+        let boxes = find_bounding_boxes(img, Background::Transparent, 0)
+            .expect("Failed to get bounding box");
 
         assert_eq!(1, boxes.len());
         assert_eq!(
@@ -320,7 +421,8 @@ mod test {
     #[test]
     fn rect_test() {
         let img = open("test_inputs/rect.png").expect("Failed to open image");
-        let boxes = find_bounding_boxes(img).expect("Failed to get bounding box");
+        let boxes = find_bounding_boxes(img, Background::Transparent, 0)
+            .expect("Failed to get bounding box");
 
         assert_eq!(1, boxes.len());
         assert_eq!(
@@ -337,7 +439,8 @@ mod test {
     #[test]
     fn rect_circle_border() {
         let img = open("test_inputs/circle_border.png").expect("Failed to open image");
-        let boxes = find_bounding_boxes(img).expect("Failed to get bounding box");
+        let boxes = find_bounding_boxes(img, Background::Transparent, 0)
+            .expect("Failed to get bounding box");
 
         assert_eq!(1, boxes.len());
         assert_eq!(
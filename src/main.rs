@@ -1,5 +1,6 @@
 use argh::FromArgs;
 use fpc::*;
+use image::imageops::FilterType;
 use image::{open, Rgba};
 use std::ffi::OsString;
 
@@ -15,6 +16,45 @@ struct Args {
     #[argh(option, default = "String::from(\"white\")")]
     background_color: String,
 
+    /// how to detect blank space in the source image: "transparent" (the
+    /// default) or a CSS color for opaque scans with a solid backdrop
+    #[argh(option, default = "String::from(\"transparent\")")]
+    background_detect: String,
+
+    /// per-channel tolerance (0-255) used when matching --background-detect
+    /// against a solid color
+    #[argh(option, default = "12")]
+    tolerance: u8,
+
+    /// border width in pixels, applied to all four sides (takes priority over --border-percent if both are given)
+    #[argh(option)]
+    border: Option<u32>,
+
+    /// border width as a percentage of the card's shorter edge, applied to all four sides
+    #[argh(option)]
+    border_percent: Option<f64>,
+
+    /// corner radius, in pixels, for the framed card (default: 1/8in at 300dpi)
+    #[argh(option, default = "((1.0 / 8.0) * 300.0) as u32")]
+    corner_radius: u32,
+
+    /// how to fill the border region: "solid" (the default, using
+    /// --background-color) or "stretch" (bleed the card content under the frame)
+    #[argh(option, default = "String::from(\"solid\")")]
+    border_fill: String,
+
+    /// physical resolution, in dots per inch, to embed in and rescale output images to
+    #[argh(option, default = "72")]
+    output_dpi: u32,
+
+    /// reconstruction filter used when resizing: "nearest", "triangle" (default), "catmull-rom", "gaussian", or "lanczos3"
+    #[argh(option, default = "String::from(\"triangle\")")]
+    resample: String,
+
+    /// scan each cell for a QR code; payload decoding is not implemented, so a cell with a QR code is reported on stdout and no sidecar '<output_stem>-<num>.txt' is written
+    #[argh(switch)]
+    read_card_ids: bool,
+
     /// the maximum width of the output images
     #[argh(option, default = "750")]
     max_width: u32,
@@ -36,6 +76,38 @@ fn main() -> fpc::Result<()> {
     let background_color = csscolorparser::parse(&args.background_color)
         .unwrap()
         .to_rgba16();
+    let background = if args.background_detect.eq_ignore_ascii_case("transparent") {
+        Background::Transparent
+    } else {
+        let color = csscolorparser::parse(&args.background_detect)
+            .unwrap()
+            .to_rgba16();
+        Background::Color(Rgba(color))
+    };
+    let side = match (args.border, args.border_percent) {
+        (Some(px), _) => Side::Absolute(px),
+        (None, Some(pct)) => Side::Percent(pct),
+        (None, None) => Side::Absolute(0),
+    };
+    let resample = match args.resample.as_str() {
+        s if s.eq_ignore_ascii_case("nearest") => FilterType::Nearest,
+        s if s.eq_ignore_ascii_case("triangle") => FilterType::Triangle,
+        s if s.eq_ignore_ascii_case("catmull-rom") => FilterType::CatmullRom,
+        s if s.eq_ignore_ascii_case("gaussian") => FilterType::Gaussian,
+        s if s.eq_ignore_ascii_case("lanczos3") => FilterType::Lanczos3,
+        other => panic!("Unknown --resample filter: {}", other),
+    };
+    let fill = match args.border_fill.as_str() {
+        s if s.eq_ignore_ascii_case("solid") => FillMode::Solid(Rgba(background_color)),
+        s if s.eq_ignore_ascii_case("stretch") => FillMode::Stretch,
+        other => panic!("Unknown --border-fill mode: {}", other),
+    };
+    let border = Border {
+        sides: Sides::uniform(side),
+        fill,
+        resample,
+        corner_radius_px: args.corner_radius,
+    };
     // TODO: use the input_image to get the default stem. Otherwise, multiple images will overwrite.
     for image_file in &args.input_images {
         let img = open(image_file).expect("Failed to open image");
@@ -43,7 +115,12 @@ fn main() -> fpc::Result<()> {
             &img,
             args.aspect_ratio,
             args.max_width,
-            Rgba(background_color),
+            &border,
+            background,
+            args.tolerance,
+            args.output_dpi,
+            resample,
+            args.read_card_ids,
             &args.output_directory,
             &args.output_stem,
         )?;
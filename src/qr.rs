@@ -0,0 +1,285 @@
+//! Locates a QR code inside an extracted card image and recovers its module
+//! (bit) matrix, so a caller can read back a per-card identifier that was
+//! printed alongside the art. [`decode`] stops short of actual Reed-Solomon
+//! error correction and symbol-format parsing, so [`locate_card_id`] returns
+//! [`FpcError::QrDecodingUnsupported`](crate::FpcError::QrDecodingUnsupported)
+//! rather than silently reporting "no QR code" when it finds a plausible
+//! symbol it can't yet decode.
+
+use crate::grid_finder::{apply_homography, sample_bilinear, square_to_quad, Point};
+use crate::{FpcError, Result};
+use image::{DynamicImage, GenericImageView};
+
+// Side length, in modules, of a version-1 QR code (the smallest symbol).
+// Finder-pattern detection doesn't depend on the version, but module
+// sampling does, so this is the only size supported for now.
+const MODULES_PER_SIDE: u32 = 21;
+
+/// The bounding box of a connected component of "dark" pixels, found while
+/// searching for finder patterns.
+#[derive(Debug, Clone, Copy)]
+struct Blob {
+    min_x: u32,
+    min_y: u32,
+    max_x: u32,
+    max_y: u32,
+    area: u32,
+}
+
+impl Blob {
+    fn width(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    fn height(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
+
+    fn center(&self) -> Point {
+        Point {
+            x: (self.min_x + self.max_x) as f64 / 2.0,
+            y: (self.min_y + self.max_y) as f64 / 2.0,
+        }
+    }
+
+    // A finder pattern's outer dark ring is ~40-60% filled, unlike the
+    // near-100% of a solid icon or the near-0% of thin text strokes.
+    fn looks_like_finder_pattern(&self) -> bool {
+        let (w, h) = (self.width() as f64, self.height() as f64);
+        let squareness = w.min(h) / w.max(h);
+        let fill_ratio = self.area as f64 / (w * h);
+        squareness >= 0.8 && self.area >= 24 && (0.35..=0.65).contains(&fill_ratio)
+    }
+}
+
+// Thresholds `img` to a dark/light mask using the mean luma as the cutoff.
+fn threshold_to_binary(img: &DynamicImage) -> (Vec<bool>, u32, u32) {
+    let (width, height) = img.dimensions();
+    let luma = img.to_luma8();
+    let total: u64 = luma.pixels().map(|p| p.0[0] as u64).sum();
+    let mean = (total / (width as u64 * height as u64).max(1)) as u8;
+
+    let mask = luma.pixels().map(|p| p.0[0] < mean).collect();
+    (mask, width, height)
+}
+
+fn find_components(mask: &[bool], width: u32, height: u32) -> Vec<Blob> {
+    let mut visited = vec![false; mask.len()];
+    let mut blobs = vec![];
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+
+    for y in 0..height {
+        for x in 0..width {
+            if visited[index(x, y)] || !mask[index(x, y)] {
+                continue;
+            }
+
+            let mut stack = vec![(x, y)];
+            let mut blob = Blob {
+                min_x: x,
+                min_y: y,
+                max_x: x,
+                max_y: y,
+                area: 0,
+            };
+            while let Some((cx, cy)) = stack.pop() {
+                if visited[index(cx, cy)] || !mask[index(cx, cy)] {
+                    continue;
+                }
+                visited[index(cx, cy)] = true;
+                blob.area += 1;
+                blob.min_x = blob.min_x.min(cx);
+                blob.min_y = blob.min_y.min(cy);
+                blob.max_x = blob.max_x.max(cx);
+                blob.max_y = blob.max_y.max(cy);
+
+                if cx > 0 {
+                    stack.push((cx - 1, cy));
+                }
+                if cx + 1 < width {
+                    stack.push((cx + 1, cy));
+                }
+                if cy > 0 {
+                    stack.push((cx, cy - 1));
+                }
+                if cy + 1 < height {
+                    stack.push((cx, cy + 1));
+                }
+            }
+            blobs.push(blob);
+        }
+    }
+    blobs
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+// Orders three finder-pattern centers into top-left/top-right/bottom-left.
+// The longest side of the triangle they form is the tr-bl diagonal, so
+// whichever point isn't an endpoint of it is top-left; the other two are
+// told apart by the orientation (cross product) of the turn from top-left.
+fn order_finder_patterns(a: Point, b: Point, c: Point) -> (Point, Point, Point) {
+    let d_ab = distance(a, b);
+    let d_bc = distance(b, c);
+    let d_ca = distance(c, a);
+
+    let (tl, p, q) = if d_ab >= d_bc && d_ab >= d_ca {
+        (c, a, b)
+    } else if d_bc >= d_ca {
+        (a, b, c)
+    } else {
+        (b, c, a)
+    };
+
+    let cross = (p.x - tl.x) * (q.y - tl.y) - (p.y - tl.y) * (q.x - tl.x);
+    if cross > 0.0 {
+        (tl, p, q)
+    } else {
+        (tl, q, p)
+    }
+}
+
+// Samples the module grid of the QR symbol whose finder-pattern centers are
+// `tl,tr,bl` (with the fourth center completed as a parallelogram) into a
+// row-major bool matrix.
+fn locate_modules(img: &DynamicImage, tl: Point, tr: Point, bl: Point) -> Vec<Vec<bool>> {
+    let br = Point {
+        x: tr.x + bl.x - tl.x,
+        y: tr.y + bl.y - tl.y,
+    };
+    let homography = square_to_quad(tl, tr, br, bl);
+    let source = img.to_rgba16();
+
+    // Same global-mean luma threshold as `threshold_to_binary`, applied to
+    // decide dark (module set) vs. light (module clear) per sampled pixel.
+    let total: u64 = source.pixels().map(|p| luma(p.0) as u64).sum();
+    let mean = (total / (source.width() as u64 * source.height() as u64).max(1)) as u16;
+
+    // Finder-pattern centers sit 3.5 modules in from the symbol's edges, so
+    // the center-to-center span covers MODULES_PER_SIDE - 7 modules.
+    let span = (MODULES_PER_SIDE - 7) as f64;
+
+    (0..MODULES_PER_SIDE)
+        .map(|row| {
+            let v = (row as f64 + 0.5 - 3.5) / span;
+            (0..MODULES_PER_SIDE)
+                .map(|col| {
+                    let u = (col as f64 + 0.5 - 3.5) / span;
+                    let (sx, sy) = apply_homography(&homography, u, v);
+                    luma(sample_bilinear(&source, sx, sy).0) < mean
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn luma(rgba: [u16; 4]) -> u16 {
+    ((rgba[0] as u32 * 299 + rgba[1] as u32 * 587 + rgba[2] as u32 * 114) / 1000) as u16
+}
+
+// Not implemented: a real decoder needs Reed-Solomon error correction and
+// the zig-zag data/mask-pattern layout. Reports QrDecodingUnsupported rather
+// than pretending to have found nothing.
+fn decode(_modules: &[Vec<bool>]) -> Result<String> {
+    Err(FpcError::QrDecodingUnsupported)
+}
+
+/// Locates a QR code inside `cell` and decodes its payload, if present.
+/// Returns `Ok(None)` when there's no QR code in `cell` at all, or
+/// `Err(FpcError::QrDecodingUnsupported)` when a plausible symbol is found
+/// but decoding isn't implemented yet.
+pub fn locate_card_id(cell: &DynamicImage) -> Result<Option<String>> {
+    let (mask, width, height) = threshold_to_binary(cell);
+    let candidates: Vec<Blob> = find_components(&mask, width, height)
+        .into_iter()
+        .filter(Blob::looks_like_finder_pattern)
+        .collect();
+
+    if candidates.len() < 3 {
+        return Ok(None);
+    }
+
+    // Finder patterns are large, so the three biggest connected components
+    // are overwhelmingly likely to be the genuine ones rather than noise.
+    let mut by_area = candidates;
+    by_area.sort_by_key(|blob| std::cmp::Reverse(blob.area));
+    let (a, b, c) = (
+        by_area[0].center(),
+        by_area[1].center(),
+        by_area[2].center(),
+    );
+
+    let (tl, tr, bl) = order_finder_patterns(a, b, c);
+    let modules = locate_modules(cell, tl, tr, bl);
+    decode(&modules).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{order_finder_patterns, Blob, Point};
+
+    #[test]
+    fn order_finder_patterns_identifies_known_corners() {
+        let tl = Point { x: 0.0, y: 0.0 };
+        let tr = Point { x: 10.0, y: 0.0 };
+        let bl = Point { x: 0.0, y: 10.0 };
+
+        // However the three centers are handed in, the longest side is the
+        // tr-bl diagonal, so the result should always name the same corners.
+        assert_eq!(order_finder_patterns(tl, tr, bl), (tl, tr, bl));
+        assert_eq!(order_finder_patterns(tr, bl, tl), (tl, tr, bl));
+        assert_eq!(order_finder_patterns(bl, tl, tr), (tl, tr, bl));
+    }
+
+    #[test]
+    fn order_finder_patterns_handles_a_different_triangle() {
+        let tl = Point { x: 5.0, y: 5.0 };
+        let tr = Point { x: 25.0, y: 6.0 };
+        let bl = Point { x: 4.0, y: 30.0 };
+
+        assert_eq!(order_finder_patterns(tl, tr, bl), (tl, tr, bl));
+        assert_eq!(order_finder_patterns(bl, tr, tl), (tl, tr, bl));
+    }
+
+    #[test]
+    fn ring_shaped_blob_looks_like_finder_pattern() {
+        // A 7x7 bounding box with its 5x5 center excluded, like a finder
+        // pattern's outer dark ring: area 24 of 49, ~49% filled.
+        let blob = Blob {
+            min_x: 0,
+            min_y: 0,
+            max_x: 6,
+            max_y: 6,
+            area: 24,
+        };
+        assert!(blob.looks_like_finder_pattern());
+    }
+
+    #[test]
+    fn solid_square_icon_does_not_look_like_finder_pattern() {
+        // A fully-filled square, like ordinary card art (a pip or icon),
+        // should not be mistaken for a finder pattern's ring.
+        let blob = Blob {
+            min_x: 0,
+            min_y: 0,
+            max_x: 9,
+            max_y: 9,
+            area: 100,
+        };
+        assert!(!blob.looks_like_finder_pattern());
+    }
+
+    #[test]
+    fn thin_blob_does_not_look_like_finder_pattern() {
+        let blob = Blob {
+            min_x: 0,
+            min_y: 0,
+            max_x: 49,
+            max_y: 2,
+            area: 100,
+        };
+        assert!(!blob.looks_like_finder_pattern());
+    }
+}
@@ -0,0 +1,133 @@
+//! Browser entry point for the extraction pipeline, exposed via
+//! `wasm-bindgen`. Accepts an in-memory image plus the native CLI's sizing,
+//! border and output-dpi parameters and returns each decoded cell's bounds
+//! alongside its rendered card PNG bytes, rather than writing anything to
+//! disk — this is what lets `extract_grid` run client-side in a
+//! browser-based card-cutting tool. Blank space is always detected as
+//! transparency, and resizing always uses the triangle filter.
+
+use crate::grid_finder::find_grid_cells;
+use crate::{render_cell, Background, Border, FillMode, Side, Sides};
+use image::imageops::FilterType;
+use image::{load_from_memory, ImageOutputFormat, Rgba};
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+/// One rendered card: the bounds it occupied on the source sheet, and the
+/// PNG bytes of the framed, dpi-rescaled output.
+#[wasm_bindgen]
+pub struct ExtractedCard {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    png: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl ExtractedCard {
+    #[wasm_bindgen(getter)]
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The rendered card, as PNG-encoded bytes.
+    #[wasm_bindgen(getter)]
+    pub fn png(&self) -> Vec<u8> {
+        self.png.clone()
+    }
+}
+
+/// Finds the grid cells in `image_bytes` and renders each one into a framed,
+/// dpi-rescaled card PNG, all in memory. Mirrors the native CLI's
+/// `aspect_ratio`/`max_width`/`background_color`/border/`output_dpi`
+/// parameters; `border_px` takes priority over `border_percent` when both
+/// are given, matching `main`'s precedence. `border_fill` mirrors the CLI's
+/// `--border-fill`: "solid" (the default, using `background_color`) or
+/// "stretch" to bleed the card content under the frame instead.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn extract_grid(
+    image_bytes: &[u8],
+    aspect_ratio: f64,
+    max_width: u32,
+    background_color: &str,
+    border_px: Option<u32>,
+    border_percent: Option<f64>,
+    border_fill: &str,
+    corner_radius_px: u32,
+    output_dpi: u32,
+) -> Result<Vec<ExtractedCard>, JsValue> {
+    let img = load_from_memory(image_bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let color = csscolorparser::parse(background_color)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?
+        .to_rgba16();
+
+    let side = match (border_px, border_percent) {
+        (Some(px), _) => Side::Absolute(px),
+        (None, Some(pct)) => Side::Percent(pct),
+        (None, None) => Side::Absolute(0),
+    };
+    let fill = match border_fill {
+        s if s.eq_ignore_ascii_case("solid") => FillMode::Solid(Rgba(color)),
+        s if s.eq_ignore_ascii_case("stretch") => FillMode::Stretch,
+        other => {
+            return Err(JsValue::from_str(&format!(
+                "Unknown border_fill mode: {other}"
+            )))
+        }
+    };
+    let border = Border {
+        sides: Sides::uniform(side),
+        fill,
+        resample: FilterType::Triangle,
+        corner_radius_px,
+    };
+
+    let cells = find_grid_cells(&img, Background::Transparent, 0)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    cells
+        .iter()
+        .map(|rect| {
+            let rendered = render_cell(
+                &img,
+                rect,
+                aspect_ratio,
+                max_width,
+                &border,
+                output_dpi,
+                FilterType::Triangle,
+            )
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            let mut png = vec![];
+            rendered
+                .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            Ok(ExtractedCard {
+                x: rect.x,
+                y: rect.y,
+                width: rect.width,
+                height: rect.height,
+                png,
+            })
+        })
+        .collect()
+}